@@ -12,7 +12,7 @@ pub(crate) struct AnyMap(
 impl AnyMap {
     pub(crate) fn insert<T: Send + Sync + 'static>(&mut self, value: Box<T>) -> Option<Box<T>> {
         self.0
-            .insert(TypeId::of::<T>(), Box::new(value))
+            .insert(TypeId::of::<T>(), value)
             .and_then(|boxed| boxed.downcast().ok())
     }
 
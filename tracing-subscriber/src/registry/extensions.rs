@@ -1,10 +1,108 @@
 use super::anymap::{AnyMap, TypeMap};
 use crate::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use core::any::type_name;
-use sharded_slab::Pool;
-use std::{any::TypeId, fmt};
+use sharded_slab::{Clear, Pool};
+use std::{
+    any::TypeId,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+// `sharded_slab::Pool::get` only ever hands back a read-only `pool::Ref`
+// for a slot located by key — `create`/`create_with` are the only APIs
+// that yield mutable access, and only for a slot at the moment it's
+// allocated. Wrapping each slot's value in a `RwLock` is what lets
+// `get`/`get_mut`/`try_insert`/`entry` access an already-allocated slot, by
+// locking it rather than needing `Pool` itself to provide that access.
+//
+// A `RwLock` (rather than a `Mutex`) is used specifically so that two
+// concurrent `get::<T>()` calls for the same type — including a call
+// nested inside another call's guard, e.g. recursive code holding one
+// `Ref` while taking another — don't deadlock each other the way they
+// would with a non-reentrant `Mutex`. Writers (`get_mut`/`try_insert`/
+// `entry`) still exclude all other access to the slot, as they must.
+type ExtPool<T> = Pool<RwLock<Option<T>>>;
+
+impl<T> Clear for RwLock<Option<T>> {
+    fn clear(&mut self) {
+        *self.get_mut().expect("RwLock poisoned") = None;
+    }
+}
+
+/// Extends the lifetime of a reference obtained through a lock guard
+/// that's about to be dropped.
+///
+/// # Safety
+///
+/// Extension pools are inserted into the backing `AnyMap` at most once per
+/// type and are never removed from it — only the slots *within* a pool
+/// are cleared, not the pool entry itself — so a pool, and the slots
+/// within it, have a stable address for as long as the owning
+/// `RwLock<AnyMap>` is alive. It's therefore sound to keep using a
+/// `&RwLock` located through a (now-dropped) `RwLock`/`Pool` guard, as long
+/// as all further access to the referent continues to go through that
+/// `RwLock`.
+///
+/// The concurrent-entry-creation path (two threads racing `entry`/`try_insert`
+/// for a type with no pool yet, both taking the slow `write()` branch)
+/// relies on the backing `RwLock<AnyMap>` to serialize pool creation, and
+/// on `sharded_slab::Pool` itself to serialize slot allocation — this
+/// function only ever extends the lifetime of a reference to memory that
+/// is already valid and already synchronized by one of those two locks.
+unsafe fn extend_lifetime<'a, T: ?Sized>(r: &T) -> &'a T {
+    &*(r as *const T)
+}
+
+/// A guard granting shared access to an extension, returned by
+/// [`Extensions::get`].
+///
+/// Access to the extension is guarded by a per-extension `RwLock`, so this
+/// type (rather than a bare reference) is what's handed back — the
+/// `RwLock` stays locked for as long as the guard is alive.
+///
+/// Note that, unlike the `Option<&T>` this method returned previously,
+/// `Ref` holds a lock guard and is therefore **not `Send`**: it cannot be
+/// held across an `.await` point or moved to another thread. This is an
+/// intentional, unavoidable consequence of `get` now supporting mutation
+/// of already-allocated extensions (see [`ExtensionsMut::entry`]); code
+/// that needs to carry an extension's value across an await point should
+/// clone it out of the guard instead of holding the guard itself.
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub struct Ref<'a, T> {
+    guard: RwLockReadGuard<'a, Option<T>>,
+}
+
+impl<'a, T> Deref for Ref<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.as_ref().expect("Extensions corrupted")
+    }
+}
+
+/// A guard granting mutable access to an extension, returned by
+/// [`ExtensionsMut::get_mut`] and [`ExtensionsMut::try_insert`].
+///
+/// Access to the extension is guarded by a per-extension `RwLock`, so this
+/// type (rather than a bare reference) is what's handed back — the
+/// `RwLock` stays locked for as long as the guard is alive. As with
+/// [`Ref`], this guard is **not `Send`**.
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub struct RefMut<'a, T> {
+    guard: RwLockWriteGuard<'a, Option<T>>,
+}
 
-type ExtPool<T> = Pool<Option<T>>;
+impl<'a, T> Deref for RefMut<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.as_ref().expect("Extensions corrupted")
+    }
+}
+
+impl<'a, T> DerefMut for RefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().expect("Extensions corrupted")
+    }
+}
 
 /// An immutable, read-only reference to a Span's extensions.
 #[cfg_attr(docsrs, doc(cfg(feature = "std")))]
@@ -24,11 +122,21 @@ impl<'a> Extensions<'a> {
     }
 
     /// Immutably borrows a type previously inserted into this `Extensions`.
-    pub fn get<T: 'static>(&self) -> Option<&T> {
+    ///
+    /// Note that this returns `Option<Ref<'_, T>>` rather than
+    /// `Option<&T>` — see [`Ref`]'s documentation for why, including the
+    /// resulting `!Send` guard.
+    pub fn get<T: 'static>(&self) -> Option<Ref<'_, T>> {
         let &key = self.keys.get(&TypeId::of::<T>())?;
         let pool = self.store.get::<ExtPool<T>>()?;
-        let ext = pool.get(key)?;
-        ext.as_ref()
+        let slot = pool.get(key)?;
+        // SAFETY: see `extend_lifetime`'s docs. `slot` (sharded_slab's own
+        // guard) is about to be dropped, but the `RwLock` it points to
+        // lives at least as long as `self.store`.
+        let lock: &RwLock<Option<T>> = unsafe { extend_lifetime(&*slot) };
+        Some(Ref {
+            guard: lock.read().expect("RwLock poisoned"),
+        })
     }
 }
 
@@ -71,12 +179,28 @@ impl<'a> ExtensionsMut<'a> {
     ///
     /// [subscriber]: crate::subscribe::Subscribe
     pub fn insert<T: Send + Sync + 'static>(&mut self, val: T) {
-        if self.keys.contains_key(&TypeId::of::<T>()) {
+        if self.try_insert(val).is_err() {
             panic!(
                 "Extensions already contain a value for type `{:?}`",
                 type_name::<T>()
             );
         }
+    }
+
+    /// Insert a type into this `Extensions`, without overwriting or
+    /// panicking if a value of the same type already exists.
+    ///
+    /// If `T` is already present in `Extensions`, the provided `val` is
+    /// handed back as `Err(val)` instead of being stored. This lets
+    /// composable subscribers that can't be sure another layer hasn't
+    /// already registered the same newtype recover gracefully rather than
+    /// panicking, as [`insert`] does.
+    ///
+    /// [`insert`]: Self::insert
+    pub fn try_insert<T: Send + Sync + 'static>(&mut self, val: T) -> Result<RefMut<'_, T>, T> {
+        if self.keys.contains_key(&TypeId::of::<T>()) {
+            return Err(val);
+        }
 
         // We try a read lock first to reduce contention on the global RwLock.
         let mut store = self.store.read().expect("Mutex poisoned");
@@ -94,46 +218,162 @@ impl<'a> ExtensionsMut<'a> {
         };
 
         let key = pool
-            .create_with(|place| *place = Some(val))
+            .create_with(|place| *place = RwLock::new(Some(val)))
             .expect("Unable to allocate another span extension");
 
         self.keys.insert(TypeId::of::<T>(), key);
+
+        let slot = pool.get(key).expect("Extensions corrupted");
+        // SAFETY: see `extend_lifetime`'s docs. `slot` is about to be
+        // dropped, but the `RwLock` it points to lives at least as long as
+        // `self.store`, which is what the elided `&mut self` return
+        // lifetime is actually bounded by.
+        let lock: &RwLock<Option<T>> = unsafe { extend_lifetime(&*slot) };
+        Ok(RefMut {
+            guard: lock.write().expect("RwLock poisoned"),
+        })
     }
 
     /// Replaces an existing `T` into this extensions.
     ///
     /// If `T` is not present, `Option::None` will be returned.
-    pub fn replace<T: Send + Sync + 'static>(&mut self, val: T) -> Option<()> {
-        let FIXME_BREAKING_CHANGE = ();
-
+    pub fn replace<T: Send + Sync + 'static>(&mut self, val: T) -> Option<T> {
         let previous = self.remove::<T>();
         self.insert(val);
         previous
     }
 
     /// Get a mutable reference to a type previously inserted on this `ExtensionsMut`.
-    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+    pub fn get_mut<T: 'static>(&mut self) -> Option<RefMut<'_, T>> {
         let &key = self.keys.get(&TypeId::of::<T>())?;
         let store = self.store.read().expect("Mutex poisoned");
         let pool = store.get::<ExtPool<T>>()?;
-        let ext = pool.get(key)?;
-
-        ext.as_mut()
+        let slot = pool.get(key)?;
+        // SAFETY: see `extend_lifetime`'s docs.
+        let lock: &RwLock<Option<T>> = unsafe { extend_lifetime(&*slot) };
+        Some(RefMut {
+            guard: lock.write().expect("RwLock poisoned"),
+        })
     }
 
     /// Remove a type from this `Extensions`.
     ///
     /// If a extension of this type existed, it will be returned.
-    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<()> {
-        let FIXME_BREAKING_CHANGE = ();
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        let key = self.keys.remove(&TypeId::of::<T>())?;
+        let store = self.store.read().expect("Mutex poisoned");
+        let pool = store.get::<ExtPool<T>>().expect("Extensions corrupted");
+
+        let val = pool
+            .get(key)
+            .and_then(|slot| slot.write().expect("RwLock poisoned").take());
+        // Clear (rather than fully remove) the slab slot so its capacity is
+        // retained for the next extension of this type.
+        pool.clear(key);
+
+        val
+    }
+
+    /// Gets the entry for the given type `T` in this `Extensions`, for
+    /// in-place manipulation.
+    ///
+    /// This avoids the awkward `get_mut` + `insert` dance (and the panic
+    /// that `insert` would raise if an extension of this type were already
+    /// present) when a subscriber only wants to populate an extension if
+    /// it isn't there yet.
+    pub fn entry<T: Send + Sync + 'static>(&mut self) -> Entry<'_, T> {
+        let key = match self.keys.get(&TypeId::of::<T>()) {
+            Some(&key) => key,
+            None => {
+                // We try a read lock first to reduce contention on the global RwLock.
+                let mut store = self.store.read().expect("Mutex poisoned");
+                let pool = match store.get::<ExtPool<T>>() {
+                    Some(pool) => pool,
+                    None => {
+                        drop(store);
+                        let mut map = self.store.write().expect("Mutex poisoned");
+                        // Another thread may have created the pool while no
+                        // lock was held; only insert it if it's still missing.
+                        if map.get::<ExtPool<T>>().is_none() {
+                            map.insert(Box::new(ExtPool::<T>::default()));
+                        }
+                        drop(map);
+                        store = self.store.read().expect("Mutex poisoned");
+                        store.get().unwrap()
+                    }
+                };
+
+                let key = pool
+                    .create_with(|place| *place = RwLock::new(None))
+                    .expect("Unable to allocate another span extension");
+
+                self.keys.insert(TypeId::of::<T>(), key);
+                key
+            }
+        };
 
         let store = self.store.read().expect("Mutex poisoned");
-        self.keys.remove(&TypeId::of::<T>()).map(|key| {
-            store
-                .get::<ExtPool<T>>()
-                .expect("Extensions corrupted")
-                .clear(key); // FIXME(CAD97): s/clear(key);/remove(key)/
-        })
+        let pool = store.get::<ExtPool<T>>().expect("Extensions corrupted");
+        let slot = pool.get(key).expect("Extensions corrupted");
+        // SAFETY: see `extend_lifetime`'s docs.
+        let lock: &RwLock<Option<T>> = unsafe { extend_lifetime(&*slot) };
+
+        // Lock the slot's `RwLock` for the `Entry`'s own lifetime, rather
+        // than re-acquiring it on every accessor call. This is what
+        // actually bounds the `&mut T` returned by
+        // `or_insert`/`or_default` to the `Entry` itself: locking a
+        // freshly-looked-up slot inside each accessor would return a
+        // reference into a guard that dies at the end of that call, which
+        // doesn't borrow-check.
+        Entry {
+            guard: lock.write().expect("RwLock poisoned"),
+        }
+    }
+}
+
+/// A view into the slot for a single type in a [`ExtensionsMut`], which may
+/// either be vacant or occupied.
+///
+/// This is returned by [`ExtensionsMut::entry`].
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub struct Entry<'a, T> {
+    guard: RwLockWriteGuard<'a, Option<T>>,
+}
+
+impl<'a, T: Send + Sync + 'static> Entry<'a, T> {
+    /// Ensures a value is in the entry by inserting `default` if it is
+    /// empty, and returns a mutable reference to the value.
+    pub fn or_insert(&mut self, default: T) -> &mut T {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensures a value is in the entry by inserting the result of `default`
+    /// if it is empty, and returns a mutable reference to the value.
+    pub fn or_insert_with(&mut self, default: impl FnOnce() -> T) -> &mut T {
+        if self.guard.is_none() {
+            *self.guard = Some(default());
+        }
+
+        self.guard.as_mut().unwrap()
+    }
+
+    /// Ensures a value is in the entry by inserting the default value if it
+    /// is empty, and returns a mutable reference to the value.
+    pub fn or_default(&mut self) -> &mut T
+    where
+        T: Default,
+    {
+        self.or_insert_with(T::default)
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any
+    /// potential insert.
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Some(val) = self.guard.as_mut() {
+            f(val);
+        }
+
+        self
     }
 }
 
@@ -229,3 +469,136 @@ impl fmt::Debug for ExtensionsMut<'_> {
 //         );
 //     }
 // }
+
+#[cfg(test)]
+mod entry_tests {
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Counter(u32);
+
+    fn new_extensions() -> (RwLock<AnyMap>, RwLock<TypeMap<usize>>) {
+        (
+            RwLock::new(AnyMap::default()),
+            RwLock::new(TypeMap::default()),
+        )
+    }
+
+    #[test]
+    fn entry_or_default_inserts_and_reuses_slot() {
+        let (store, keys) = new_extensions();
+        let mut extensions = ExtensionsMut::new(&store, keys.write().expect("Mutex poisoned"));
+
+        extensions.entry::<Counter>().or_default().0 += 1;
+        assert_eq!(*extensions.get_mut::<Counter>().unwrap(), Counter(1));
+
+        // A second `entry()` call reuses the existing slot rather than
+        // overwriting it with another default.
+        extensions.entry::<Counter>().or_default().0 += 1;
+        assert_eq!(*extensions.get_mut::<Counter>().unwrap(), Counter(2));
+    }
+
+    #[test]
+    fn entry_and_modify_only_runs_when_occupied() {
+        let (store, keys) = new_extensions();
+        let mut extensions = ExtensionsMut::new(&store, keys.write().expect("Mutex poisoned"));
+
+        // `and_modify` on a vacant entry is a no-op; the closure must not run.
+        extensions
+            .entry::<Counter>()
+            .and_modify(|c| c.0 += 1)
+            .or_insert(Counter(10));
+        assert_eq!(*extensions.get_mut::<Counter>().unwrap(), Counter(10));
+
+        extensions
+            .entry::<Counter>()
+            .and_modify(|c| c.0 += 1)
+            .or_insert(Counter(0));
+        assert_eq!(*extensions.get_mut::<Counter>().unwrap(), Counter(11));
+    }
+
+    #[test]
+    fn remove_and_replace_return_owned_value() {
+        let (store, keys) = new_extensions();
+        let mut extensions = ExtensionsMut::new(&store, keys.write().expect("Mutex poisoned"));
+
+        extensions.insert(Counter(5));
+        assert_eq!(extensions.remove::<Counter>(), Some(Counter(5)));
+        assert_eq!(extensions.remove::<Counter>(), None);
+
+        extensions.insert(Counter(1));
+        assert_eq!(extensions.replace(Counter(2)), Some(Counter(1)));
+        assert_eq!(*extensions.get_mut::<Counter>().unwrap(), Counter(2));
+    }
+
+    #[test]
+    fn get_is_reentrant_for_shared_reads() {
+        let (store, keys) = new_extensions();
+        {
+            let mut extensions = ExtensionsMut::new(&store, keys.write().expect("Mutex poisoned"));
+            extensions.insert(Counter(1));
+        }
+
+        let extensions = Extensions::new(
+            store.read().expect("Mutex poisoned"),
+            keys.read().expect("Mutex poisoned"),
+        );
+
+        // Holding one `Ref` while taking a second `get::<T>()` for the same
+        // type, from the same thread, must not deadlock: unlike a
+        // non-reentrant `Mutex`, a `RwLock` allows any number of
+        // concurrent readers.
+        let first = extensions.get::<Counter>().unwrap();
+        let second = extensions.get::<Counter>().unwrap();
+        assert_eq!(*first, Counter(1));
+        assert_eq!(*second, Counter(1));
+    }
+
+    #[test]
+    fn concurrent_entry_creation_does_not_deadlock() {
+        // Stress the lazy, double-checked-locking pool creation in `entry`:
+        // several threads race to be the first to allocate the `ExtPool<Counter>`
+        // behind the (registry-global) `store` lock, each from its own
+        // span-local `keys` map, the way concurrent spans sharing one
+        // registry actually would.
+        let store = RwLock::new(AnyMap::default());
+        let seen_keys = std::sync::Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let keys = RwLock::new(TypeMap::<usize>::default());
+                    let mut extensions = ExtensionsMut::new(&store, keys.write().unwrap());
+                    extensions.entry::<Counter>().or_default().0 += 1;
+                    // Release the `keys` write guard before reading `keys`.
+                    drop(extensions);
+                    let &key = keys.read().unwrap().get(&TypeId::of::<Counter>()).unwrap();
+                    seen_keys.lock().unwrap().push(key);
+                });
+            }
+        });
+
+        let seen_keys = seen_keys.into_inner().unwrap();
+        assert_eq!(seen_keys.len(), 8);
+
+        let store = store.read().expect("Mutex poisoned");
+        assert!(
+            store.get::<ExtPool<Counter>>().is_some(),
+            "pool should exist after all threads have called `entry`"
+        );
+    }
+
+    #[test]
+    fn try_insert_returns_err_on_conflict() {
+        let (store, keys) = new_extensions();
+        let mut extensions = ExtensionsMut::new(&store, keys.write().expect("Mutex poisoned"));
+
+        assert_eq!(*extensions.try_insert(Counter(1)).unwrap(), Counter(1));
+        let err = extensions
+            .try_insert(Counter(2))
+            .err()
+            .expect("expected conflict");
+        assert_eq!(err, Counter(2));
+        assert_eq!(*extensions.get_mut::<Counter>().unwrap(), Counter(1));
+    }
+}